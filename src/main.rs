@@ -1,27 +1,304 @@
 use gtk::gdk;
 use gtk::prelude::*;
 
-fn load_css(settings: &gtk::Settings) {
+/// Returns `true` when dark mode should be active: an explicit
+/// `forced_preference` always wins, otherwise this falls back to
+/// libadwaita's `StyleManager` when it's available, or the legacy
+/// theme-name/`prefer-dark-theme` heuristic otherwise.
+fn prefers_dark(_settings: &gtk::Settings, forced_preference: Option<bool>) -> bool {
+    if let Some(prefer_dark) = forced_preference {
+        return prefer_dark;
+    }
+
+    #[cfg(feature = "adw")]
+    {
+        return adw::StyleManager::default().is_dark();
+    }
+
+    #[cfg(not(feature = "adw"))]
+    {
+        let theme_name = _settings.gtk_theme_name().expect("Could not get theme name.");
+
+        theme_name.to_lowercase().contains("dark") || _settings.is_gtk_application_prefer_dark_theme()
+    }
+}
+
+/// The default theme to fall back to when neither the requested theme nor
+/// its variant ships a stylesheet of its own.
+const DEFAULT_THEME: &str = "light";
+
+/// Maximum number of candidates `fallback_candidates` produces. The longest
+/// possible chain (a themed dark variant that isn't `DEFAULT_THEME` itself)
+/// is 4 entries long; capping at that bounds the search even if this
+/// function is ever extended with another link, so a missing
+/// `DEFAULT_THEME` stylesheet can't send us into a loop.
+const MAX_FALLBACK_DEPTH: u8 = 4;
+
+/// The stylesheets bundled with this application, keyed by file name.
+const BUNDLED_STYLES: &[(&str, &str)] = &[
+    ("light.css", include_str!("../styles/light.css")),
+    ("dark.css", include_str!("../styles/dark.css")),
+    ("highcontrast.css", include_str!("../styles/highcontrast.css")),
+    ("highcontrast-inverse.css", include_str!("../styles/highcontrast-inverse.css")),
+];
+
+/// An entry in `SUPPORTED_THEMES`: a GTK theme name mapped to the
+/// stylesheet that styles it, an optional variant suffix, and whether that
+/// variant is a dark counterpart (as opposed to an always-on variant like
+/// `HighContrastInverse`).
+struct SupportedTheme {
+    name: &'static str,
+    stylesheet: &'static str,
+    variant: Option<&'static str>,
+    has_dark_variant: bool,
+}
+
+const SUPPORTED_THEMES: &[SupportedTheme] = &[
+    SupportedTheme {
+        name: "HighContrastInverse",
+        stylesheet: "highcontrast",
+        variant: Some("inverse"),
+        has_dark_variant: false,
+    },
+    SupportedTheme {
+        name: "HighContrast",
+        stylesheet: "highcontrast",
+        variant: None,
+        has_dark_variant: false,
+    },
+    SupportedTheme {
+        name: "Adwaita",
+        stylesheet: DEFAULT_THEME,
+        variant: Some("dark"),
+        has_dark_variant: true,
+    },
+];
+
+fn find_supported_theme(theme_name: &str) -> Option<&'static SupportedTheme> {
+    SUPPORTED_THEMES.iter().find(|theme| theme.name == theme_name)
+}
+
+fn find_bundled_css(file_name: &str) -> Option<&'static str> {
+    BUNDLED_STYLES
+        .iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, css)| *css)
+}
+
+/// The directory `styles/<file_name>` is read from in hot-reload mode.
+#[cfg(feature = "hot-reload")]
+const STYLES_DIR: &str = "styles";
+
+/// The stylesheet currently being watched, and the provider it should be
+/// reloaded into, shared between `watch_css_file` and the watcher thread.
+#[cfg(feature = "hot-reload")]
+fn watched_target() -> &'static std::sync::Mutex<Option<(std::path::PathBuf, gtk::CssProvider)>> {
+    static TARGET: std::sync::OnceLock<std::sync::Mutex<Option<(std::path::PathBuf, gtk::CssProvider)>>> =
+        std::sync::OnceLock::new();
+
+    TARGET.get_or_init(|| std::sync::Mutex::new(None))
+}
+
+/// Spawns the single background thread and file watcher that live for the
+/// rest of the process, reloading whichever provider `watched_target`
+/// currently points at when its file changes.
+#[cfg(feature = "hot-reload")]
+fn spawn_css_watcher() -> notify::RecommendedWatcher {
+    use notify::Watcher;
+    use std::sync::mpsc::channel;
+
+    let (tx, rx) = channel();
+    let watcher = notify::recommended_watcher(tx).expect("Could not create stylesheet watcher.");
+
+    std::thread::spawn(move || {
+        for event in rx {
+            let Ok(event) = event else { continue };
+
+            if !event.kind.is_modify() {
+                continue;
+            }
+
+            let Some((path, provider)) = watched_target().lock().expect("stylesheet watch lock poisoned").clone()
+            else {
+                continue;
+            };
+
+            if !event.paths.contains(&path) {
+                continue;
+            }
+
+            let Ok(css) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+
+            glib::idle_add_once(move || provider.load_from_data(&css));
+        }
+    });
+
+    watcher
+}
+
+/// Watches `path` for writes and reloads `provider` from it whenever one
+/// occurs, so CSS tweaks show up without a recompile. The watcher thread
+/// and its `notify::Watcher` are created once and kept alive for the rest
+/// of the process; repeated calls just retarget them, so toggling the
+/// theme at runtime doesn't leak a watch/thread per toggle.
+#[cfg(feature = "hot-reload")]
+fn watch_css_file(provider: gtk::CssProvider, path: std::path::PathBuf) {
+    use notify::{RecursiveMode, Watcher};
+
+    static WATCHER: std::sync::OnceLock<std::sync::Mutex<notify::RecommendedWatcher>> = std::sync::OnceLock::new();
+
+    let previous_path = {
+        let mut target = watched_target().lock().expect("stylesheet watch lock poisoned");
+        let previous_path = target.as_ref().map(|(path, _)| path.clone());
+
+        *target = Some((path.clone(), provider));
+        previous_path
+    };
+
+    if previous_path.as_ref() == Some(&path) {
+        return;
+    }
+
+    let mut watcher = WATCHER
+        .get_or_init(|| std::sync::Mutex::new(spawn_css_watcher()))
+        .lock()
+        .expect("stylesheet watcher lock poisoned");
+
+    if let Some(previous_path) = previous_path {
+        let _ = watcher.unwatch(&previous_path);
+    }
+
+    watcher
+        .watch(&path, RecursiveMode::NonRecursive)
+        .expect("Could not watch stylesheet.");
+}
+
+/// Builds the ordered file names to try for `theme_name`/`variant`, most
+/// specific first: the theme's own variant file, the bare variant file (so
+/// a variant with no theme-specific stylesheet still reaches its plain
+/// counterpart, e.g. `dark.css`, instead of skipping straight past it), the
+/// theme's base file, and finally `DEFAULT_THEME`.
+fn fallback_candidates(theme_name: &str, variant: Option<&str>) -> Vec<String> {
+    let mut candidates = Vec::new();
+
+    if let Some(variant) = variant {
+        candidates.push(format!("{theme_name}-{variant}.css"));
+        candidates.push(format!("{variant}.css"));
+    }
+
+    candidates.push(format!("{theme_name}.css"));
+
+    if theme_name != DEFAULT_THEME {
+        candidates.push(format!("{DEFAULT_THEME}.css"));
+    }
+
+    candidates.truncate(MAX_FALLBACK_DEPTH as usize);
+    candidates
+}
+
+/// Loads the first stylesheet in `fallback_candidates(theme_name, variant)`
+/// that actually resolves, the same way GTK falls back from a variant to
+/// the base theme to a default.
+fn load_themed_css(provider: &gtk::CssProvider, theme_name: &str, variant: Option<&str>) {
+    for file_name in fallback_candidates(theme_name, variant) {
+        #[cfg(feature = "hot-reload")]
+        {
+            let path = std::path::PathBuf::from(STYLES_DIR).join(&file_name);
+
+            if path.is_file() {
+                let css = std::fs::read_to_string(&path).expect("Could not read stylesheet.");
+
+                provider.load_from_data(&css);
+                watch_css_file(provider.clone(), path);
+                return;
+            }
+        }
+
+        #[cfg(not(feature = "hot-reload"))]
+        if let Some(css) = find_bundled_css(&file_name) {
+            provider.load_from_data(css);
+            return;
+        }
+    }
+}
+
+/// Returns `true` when `theme_name` actually ships a dark variant: loads the
+/// named theme's light and dark providers and checks whether their
+/// serialized CSS differs. Themes not in `SUPPORTED_THEMES` fall back to
+/// this probe so we never apply a dark preference that wouldn't change
+/// anything.
+fn theme_supports_dark(theme_name: &str) -> bool {
+    let light = gtk::CssProvider::new();
+    light.load_named(theme_name, None);
+
+    let dark = gtk::CssProvider::new();
+    dark.load_named(theme_name, Some("dark"));
+
+    light.to_str() != dark.to_str()
+}
+
+fn load_css(settings: &gtk::Settings, forced_preference: Option<bool>) {
     let display = gdk::Display::default().expect("Could not get default display.");
     let provider = gtk::CssProvider::new();
     let priority = gtk::STYLE_PROVIDER_PRIORITY_APPLICATION;
     let theme_name = settings.gtk_theme_name().expect("Could not get theme name.");
 
-    if theme_name.to_lowercase().contains("dark") || settings.is_gtk_application_prefer_dark_theme() {
-        provider.load_from_data(include_str!("../styles/dark.css"));
-    } else {
-        provider.load_from_data(include_str!("../styles/light.css"));
-    }
+    // Re-derived on every call (system theme changes and prefer-dark-theme
+    // toggles both run through here) so switching to a dark-capable theme
+    // at runtime is picked up without a restart.
+    let supported_theme = find_supported_theme(&theme_name);
+    let has_dark_variant = supported_theme.map_or_else(|| theme_supports_dark(&theme_name), |theme| theme.has_dark_variant);
+    let prefer_dark = has_dark_variant && prefers_dark(settings, forced_preference);
+
+    let (stylesheet, variant) = match supported_theme {
+        Some(theme) if theme.has_dark_variant => (theme.stylesheet, prefer_dark.then_some(theme.variant).flatten()),
+        Some(theme) => (theme.stylesheet, theme.variant),
+        None => (DEFAULT_THEME, prefer_dark.then_some("dark")),
+    };
+
+    load_themed_css(&provider, stylesheet, variant);
 
-    
     gtk::StyleContext::add_provider_for_display(&display, &provider, priority);
 }
 
-fn on_activate(application: &gtk::Application) {
+fn on_activate(application: &gtk::Application, forced_preference: Option<bool>) {
     if let Some(settings) = gtk::Settings::default() {
-        settings.connect_gtk_application_prefer_dark_theme_notify(load_css);
-        settings.connect_gtk_theme_name_notify(load_css);
-        load_css(&settings);
+        #[cfg(feature = "adw")]
+        if let Some(prefer_dark) = forced_preference {
+            let scheme = if prefer_dark {
+                adw::ColorScheme::ForceDark
+            } else {
+                adw::ColorScheme::ForceLight
+            };
+
+            adw::StyleManager::default().set_color_scheme(scheme);
+        }
+
+        #[cfg(not(feature = "adw"))]
+        if let Some(prefer_dark) = forced_preference {
+            settings.set_gtk_application_prefer_dark_theme(prefer_dark);
+        }
+
+        settings.connect_gtk_application_prefer_dark_theme_notify(move |settings| {
+            load_css(settings, forced_preference);
+        });
+        settings.connect_gtk_theme_name_notify(move |settings| {
+            load_css(settings, forced_preference);
+        });
+        load_css(&settings, forced_preference);
+    }
+
+    #[cfg(feature = "adw")]
+    {
+        let settings = gtk::Settings::default();
+
+        adw::StyleManager::default().connect_notify_local(Some("dark"), move |_, _| {
+            if let Some(settings) = &settings {
+                load_css(settings, forced_preference);
+            }
+        });
     }
 
     let window = gtk::ApplicationWindow::new(application);
@@ -34,11 +311,189 @@ fn on_activate(application: &gtk::Application) {
     window.present();
 }
 
+/// Path to the small INI file the user's forced light/dark choice is
+/// persisted to under the per-user config dir, so it survives restarts
+/// without needing a full settings schema for a single boolean.
+fn preference_config_path() -> std::path::PathBuf {
+    glib::user_config_dir().join("gtk4-css-styling").join("config.ini")
+}
+
+/// Reads the persisted `--prefer-dark-theme`/`--prefer-light-theme` choice
+/// from a previous run, if one was ever saved.
+fn read_forced_preference() -> Option<bool> {
+    read_forced_preference_at(&preference_config_path())
+}
+
+fn read_forced_preference_at(path: &std::path::Path) -> Option<bool> {
+    let contents = std::fs::read_to_string(path).ok()?;
+
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("prefer-dark-theme="))
+        .map(|value| value.trim() == "true")
+}
+
+/// Persists an explicit `--prefer-dark-theme`/`--prefer-light-theme` choice
+/// so it survives restarts and continues overriding the system setting.
+fn write_forced_preference(prefer_dark: bool) {
+    write_forced_preference_at(&preference_config_path(), prefer_dark);
+}
+
+fn write_forced_preference_at(path: &std::path::Path, prefer_dark: bool) {
+    if let Some(config_dir) = path.parent() {
+        let _ = std::fs::create_dir_all(config_dir);
+    }
+
+    let _ = std::fs::write(path, format!("[General]\nprefer-dark-theme={prefer_dark}\n"));
+}
+
+/// Parses an explicit `--prefer-dark-theme`/`--prefer-light-theme` choice
+/// from the command line, so it can override both the system setting and
+/// any previously persisted preference for this run.
+fn parse_cli_preference() -> Option<bool> {
+    parse_preference_from_args(std::env::args())
+}
+
+fn parse_preference_from_args(args: impl Iterator<Item = String>) -> Option<bool> {
+    args.find_map(|arg| match arg.as_str() {
+        "--prefer-dark-theme" => Some(true),
+        "--prefer-light-theme" => Some(false),
+        _ => None,
+    })
+}
+
 fn main() {
+    let cli_preference = parse_cli_preference();
+
+    if let Some(prefer_dark) = cli_preference {
+        write_forced_preference(prefer_dark);
+    }
+
+    let forced_preference = cli_preference.or_else(read_forced_preference);
+
+    #[cfg(feature = "adw")]
+    let app = adw::Application::builder()
+        .application_id("com.example.gtk4-dark-mode")
+        .build();
+
+    #[cfg(not(feature = "adw"))]
     let app = gtk::Application::builder()
         .application_id("com.example.gtk4-dark-mode")
         .build();
 
-    app.connect_activate(on_activate);
+    app.connect_activate(move |application| on_activate(application.upcast_ref(), forced_preference));
     app.run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_candidates_prefers_theme_specific_dark_variant() {
+        assert_eq!(
+            fallback_candidates("Adwaita", Some("dark")),
+            vec!["Adwaita-dark.css", "dark.css", "Adwaita.css", "light.css"],
+        );
+    }
+
+    #[test]
+    fn fallback_candidates_still_reaches_bare_dark_css() {
+        // No theme-specific "Yaru-dark.css"/"Yaru.css" is bundled, so the
+        // chain must reach the bare `dark.css` rather than bottom out at
+        // the light default regardless of `prefer_dark`.
+        assert!(fallback_candidates("Yaru", Some("dark")).contains(&"dark.css".to_string()));
+    }
+
+    #[test]
+    fn fallback_candidates_without_variant_falls_back_to_default_theme() {
+        assert_eq!(fallback_candidates("Unknown", None), vec!["Unknown.css", "light.css"]);
+    }
+
+    #[test]
+    fn fallback_candidates_never_exceeds_max_fallback_depth() {
+        assert!(fallback_candidates("SomeTheme", Some("dark")).len() as u8 <= MAX_FALLBACK_DEPTH);
+    }
+
+    #[test]
+    fn find_bundled_css_resolves_known_file_names_only() {
+        assert!(find_bundled_css("dark.css").is_some());
+        assert!(find_bundled_css("does-not-exist.css").is_none());
+    }
+
+    #[test]
+    fn find_supported_theme_resolves_known_themes() {
+        assert!(find_supported_theme("Adwaita").is_some());
+        assert!(find_supported_theme("HighContrast").is_some());
+        assert!(find_supported_theme("HighContrastInverse").is_some());
+        assert!(find_supported_theme("SomeUnlistedTheme").is_none());
+    }
+
+    #[test]
+    fn adwaita_dark_preference_resolves_to_bundled_dark_css() {
+        let adwaita = find_supported_theme("Adwaita").expect("Adwaita should be a supported theme");
+        assert!(adwaita.has_dark_variant);
+
+        let file_name = fallback_candidates(adwaita.stylesheet, adwaita.variant)
+            .into_iter()
+            .find(|file_name| find_bundled_css(file_name).is_some())
+            .expect("a bundled stylesheet should resolve");
+
+        assert_eq!(file_name, "dark.css");
+    }
+
+    #[test]
+    fn high_contrast_variants_resolve_to_their_own_bundled_stylesheets() {
+        let high_contrast = find_supported_theme("HighContrast").unwrap();
+        let inverse = find_supported_theme("HighContrastInverse").unwrap();
+
+        let resolve = |theme: &SupportedTheme| {
+            fallback_candidates(theme.stylesheet, theme.variant)
+                .into_iter()
+                .find(|file_name| find_bundled_css(file_name).is_some())
+        };
+
+        assert_eq!(resolve(high_contrast), Some("highcontrast.css".to_string()));
+        assert_eq!(resolve(inverse), Some("highcontrast-inverse.css".to_string()));
+    }
+
+    fn args(values: &[&str]) -> impl Iterator<Item = String> {
+        values.iter().map(|value| value.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn parse_preference_from_args_recognizes_dark_flag() {
+        assert_eq!(parse_preference_from_args(args(&["gtk4-css-styling", "--prefer-dark-theme"])), Some(true));
+    }
+
+    #[test]
+    fn parse_preference_from_args_recognizes_light_flag() {
+        assert_eq!(parse_preference_from_args(args(&["gtk4-css-styling", "--prefer-light-theme"])), Some(false));
+    }
+
+    #[test]
+    fn parse_preference_from_args_ignores_unrelated_flags() {
+        assert_eq!(parse_preference_from_args(args(&["gtk4-css-styling", "--help"])), None);
+    }
+
+    #[test]
+    fn forced_preference_roundtrips_through_the_ini_file() {
+        let path = std::env::temp_dir().join(format!("gtk4-css-styling-test-{:?}.ini", std::thread::current().id()));
+
+        write_forced_preference_at(&path, true);
+        assert_eq!(read_forced_preference_at(&path), Some(true));
+
+        write_forced_preference_at(&path, false);
+        assert_eq!(read_forced_preference_at(&path), Some(false));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reading_a_missing_preference_file_yields_none() {
+        let path = std::env::temp_dir().join("gtk4-css-styling-test-missing.ini");
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(read_forced_preference_at(&path), None);
+    }
+}